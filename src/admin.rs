@@ -1,93 +1,199 @@
 use bincode;
 use chrono::Utc;
 use warp::{Reply, reply::Response, http::StatusCode};
-use sled::Db;
+use sled::{Db, Tree};
 
-use log::info;
+use log::{error, info};
 
 use crate::{
     AdminKey,
+    Config,
     HallEntry,
+    RecordPatch,
     RecordSubmission,
     generate_response
 };
+use crate::audit;
+use crate::crypto;
+use crate::error::HallError;
+use crate::ids;
 
-pub fn add_record(new_record: RecordSubmission, user: &AdminKey, record_db: &Db) -> Response {
-    let new_id = record_db.generate_id().unwrap();
+pub fn add_record(new_record: RecordSubmission, user: &AdminKey, record_db: &Db, config: &Config, audit_log: &Tree) -> Result<Response, HallError> {
+    let new_id = record_db.generate_id()?;
 
     // Assign this with a predictable key
     let key = format!("SI-{}", new_id);
 
-    let formed_record = HallEntry {
+    let mut formed_record = HallEntry {
         id: new_id,
+        anchor_key: None,
         reference_id: new_record.reference_id,
         affected_service: new_record.affected_service,
         date: Utc::today().naive_utc(),
         summary: new_record.summary,
         reporter: new_record.reporter,
-        reporter_handle: new_record.reporter_handle
+        reporter_handle: new_record.reporter_handle,
+        signer_public_key: None,
+        signature: None,
+        verified: false
     };
+    formed_record.generate_anchor();
 
-    let encoded_record = bincode::serialize(&formed_record).unwrap();
-    record_db.insert(key, encoded_record).unwrap();
+    if let Some(signing_key) = &user.signing_key {
+        crypto::sign_entry(&mut formed_record, signing_key)?;
+    }
+
+    let encoded_record = bincode::serialize(&formed_record)?;
+    let stored_record = crypto::encrypt_record(&encoded_record, &config.encryption_key)?;
+    record_db.insert(key, stored_record)?;
 
-    let msg = format!("Report created (ID: {})", new_id);
+    let msg = format!("Report created (ID: {})", formed_record.public_id(config));
 
-    info!("{} by {}", &msg, user.username);  
-    generate_response(&msg, StatusCode::CREATED)
+    // The record has already been committed, so a failure here must not turn
+    // a successful write into a client-visible error; log and move on.
+    if let Err(e) = audit::log_action(audit_log, &user.username, "add_record", Some(new_id)) {
+        error!("Failed to record audit log entry for add_record: {}", e);
+    }
+    info!("{} by {}", &msg, user.username);
+    Ok(generate_response(&msg, StatusCode::CREATED))
 }
 
-pub fn remove_record(record_id: u64, user: &AdminKey, record_db: &Db) -> Response {
+pub fn remove_record(record_id_slug: &str, user: &AdminKey, record_db: &Db, config: &Config, audit_log: &Tree) -> Result<Response, HallError> {
+    let record_id = ids::decode_id(config, record_id_slug)?;
     let key = format!("SI-{}", record_id);
-    if record_db.remove(key).unwrap().is_some() {
-        info!("Report removed (ID: {}) by {} ", record_id, user.username);  
-        warp::reply::with_status("", StatusCode::NO_CONTENT).into_response()
+    if record_db.remove(key)?.is_some() {
+        if let Err(e) = audit::log_action(audit_log, &user.username, "remove_record", Some(record_id)) {
+            error!("Failed to record audit log entry for remove_record: {}", e);
+        }
+        info!("Report removed (ID: {}) by {} ", record_id_slug, user.username);
+        Ok(warp::reply::with_status("", StatusCode::NO_CONTENT).into_response())
     } else {
-        let err_msg = "The requested ID doesn't exist, please try again!";
-        generate_response(err_msg, StatusCode::BAD_REQUEST)
+        Err(HallError::NotFound("The requested ID doesn't exist, please try again!".to_string()))
     }
 }
 
-pub fn update_record(updated_record: RecordSubmission, user: &AdminKey, record_db: &Db) -> Response {
-    let (key, current_id) = match updated_record.id {
-        Some(id) => (format!("SI-{}", id), id),
+pub fn update_record(updated_record: RecordSubmission, user: &AdminKey, record_db: &Db, config: &Config, audit_log: &Tree) -> Result<Response, HallError> {
+    let (key, current_id) = match &updated_record.id {
+        Some(slug) => {
+            let id = ids::decode_id(config, slug)?;
+            (format!("SI-{}", id), id)
+        }
         None => {
             let err_msg = "No ID was provided, try again!";
-            return generate_response(err_msg, StatusCode::BAD_REQUEST)
+            return Err(HallError::BadRequest(err_msg.to_string()))
         }
     };
 
-    match record_db.get(&key).unwrap() {
+    match record_db.get(&key)? {
         Some(old_record) => {
-            let old_record: HallEntry = bincode::deserialize(&old_record).unwrap();
+            let old_record = crypto::decrypt_record(&old_record, &config.encryption_key)?;
+            let old_record: HallEntry = bincode::deserialize(&old_record)?;
 
             // This assures that a record's storage key remain identical to its actual ID, so it can be found again
             if old_record.id != current_id {
                 let err_msg = "The provided ID and the record's current ID do not match, try again!";
-                return generate_response(err_msg, StatusCode::BAD_REQUEST)
+                return Err(HallError::BadRequest(err_msg.to_string()))
             }
 
             // Maybe allow the user to only send what fields they want updated?
-            let new_record = bincode::serialize(&HallEntry {
+            let mut new_record = HallEntry {
                 reference_id: updated_record.reference_id,
                 affected_service: updated_record.affected_service,
                 summary: updated_record.summary,
                 reporter: updated_record.reporter,
                 reporter_handle: updated_record.reporter_handle,
+                // The old signature no longer matches the mutated fields, so it is
+                // dropped here and recomputed below if this key signs entries.
+                signer_public_key: None,
+                signature: None,
                 ..old_record
-            })
-            .unwrap();
-            
-            record_db.insert(key, new_record).unwrap();
-            
-            let msg = format!("Report has been updated (ID: {})", current_id);
-            
+            };
+
+            // The anchor hash depends on the mutated fields, so it has to be
+            // regenerated here too, not just on the PATCH path.
+            new_record.generate_anchor();
+
+            if let Some(signing_key) = &user.signing_key {
+                crypto::sign_entry(&mut new_record, signing_key)?;
+            }
+
+            let public_id = new_record.public_id(config);
+
+            let encoded_record = bincode::serialize(&new_record)?;
+            let stored_record = crypto::encrypt_record(&encoded_record, &config.encryption_key)?;
+            record_db.insert(key, stored_record)?;
+
+            let msg = format!("Report has been updated (ID: {})", public_id);
+
+            if let Err(e) = audit::log_action(audit_log, &user.username, "update_record", Some(current_id)) {
+                error!("Failed to record audit log entry for update_record: {}", e);
+            }
             info!("{} by {}", &msg, user.username);
-            generate_response(&msg, StatusCode::OK)
+            Ok(generate_response(&msg, StatusCode::OK))
         }
         None => {
             let err_msg = "The requested ID doesn't exist, please try again!";
-            generate_response(err_msg, StatusCode::BAD_REQUEST)
-        } 
+            Err(HallError::BadRequest(err_msg.to_string()))
+        }
+    }
+}
+
+pub fn patch_record(patch: RecordPatch, user: &AdminKey, record_db: &Db, config: &Config, audit_log: &Tree) -> Result<Response, HallError> {
+    let id = ids::decode_id(config, &patch.id)?;
+    let key = format!("SI-{}", id);
+
+    match record_db.get(&key)? {
+        Some(old_record) => {
+            let old_record = crypto::decrypt_record(&old_record, &config.encryption_key)?;
+            let mut patched_record: HallEntry = bincode::deserialize(&old_record)?;
+
+            if let Some(reference_id) = patch.reference_id {
+                patched_record.reference_id = reference_id;
+            }
+            if let Some(affected_service) = patch.affected_service {
+                patched_record.affected_service = affected_service;
+            }
+            if let Some(date) = patch.date {
+                patched_record.date = date;
+            }
+            if let Some(summary) = patch.summary {
+                patched_record.summary = summary;
+            }
+            if let Some(reporter) = patch.reporter {
+                patched_record.reporter = reporter;
+            }
+            if let Some(reporter_handle) = patch.reporter_handle {
+                patched_record.reporter_handle = reporter_handle;
+            }
+
+            // The anchor hash depends on the mutated fields, so it has to be
+            // regenerated (as with `add_record`/`update_record`), and any
+            // prior signature no longer matches.
+            patched_record.generate_anchor();
+            patched_record.signer_public_key = None;
+            patched_record.signature = None;
+
+            if let Some(signing_key) = &user.signing_key {
+                crypto::sign_entry(&mut patched_record, signing_key)?;
+            }
+
+            let public_id = patched_record.public_id(config);
+
+            let encoded_record = bincode::serialize(&patched_record)?;
+            let stored_record = crypto::encrypt_record(&encoded_record, &config.encryption_key)?;
+            record_db.insert(key, stored_record)?;
+
+            let msg = format!("Report has been updated (ID: {})", public_id);
+
+            if let Err(e) = audit::log_action(audit_log, &user.username, "patch_record", Some(id)) {
+                error!("Failed to record audit log entry for patch_record: {}", e);
+            }
+            info!("{} by {}", &msg, user.username);
+            Ok(generate_response(&msg, StatusCode::OK))
+        }
+        None => {
+            let err_msg = "The requested ID doesn't exist, please try again!";
+            Err(HallError::BadRequest(err_msg.to_string()))
+        }
     }
 }
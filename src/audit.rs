@@ -0,0 +1,45 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sled::Tree;
+
+use crate::error::HallError;
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    username: &'a str,
+    action: &'a str,
+    record_id: Option<u64>,
+    timestamp: i64,
+}
+
+/// An owned, queryable view of a logged admin action, returned by `/admin/audit`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub username: String,
+    pub action: String,
+    pub record_id: Option<u64>,
+    pub timestamp: i64,
+}
+
+/// Appends a structured, queryable record of an admin action to the audit
+/// log tree so deletions and updates remain attributable after the fact.
+pub fn log_action(audit_log: &Tree, username: &str, action: &str, record_id: Option<u64>) -> Result<(), HallError> {
+    let entry = AuditEntry { username, action, record_id, timestamp: Utc::now().timestamp() };
+    let key = audit_log.generate_id()?.to_be_bytes();
+    let value = serde_json::to_vec(&entry)?;
+
+    audit_log.insert(key, value)?;
+    Ok(())
+}
+
+/// Returns every logged admin action, oldest first.
+pub fn list_actions(audit_log: &Tree) -> Result<Vec<AuditRecord>, HallError> {
+    let mut entries = Vec::with_capacity(10);
+
+    for item in audit_log.iter() {
+        let (_, value) = item?;
+        entries.push(serde_json::from_slice(&value)?);
+    }
+
+    Ok(entries)
+}
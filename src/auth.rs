@@ -0,0 +1,200 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::error::HallError;
+use crate::{AdminKey, AdminScope, Config};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The pieces of an HMAC-signed request needed to recompute and verify its
+/// signature: `X-Hall-Key-Id`, `X-Hall-Timestamp` and `X-Hall-Signature`,
+/// plus the method/path/body the signature was computed over.
+pub struct SignedRequest {
+    pub key_id: String,
+    pub timestamp: i64,
+    pub signature: String,
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// Resolves the `AdminKey` that is allowed to perform an admin action.
+///
+/// A request is authenticated either by presenting a valid `SignedRequest`,
+/// verified against `AdminKey.hmac_secret` (preferred, since that secret
+/// never crosses the wire), or, if `config.allow_bearer_auth` is set, by
+/// sending `AdminKey.key` as a bearer `Authorization` header. The two
+/// secrets are independent, so observing one never compromises the other.
+pub fn check_admin_permissions<'a>(
+    config: &'a Config,
+    auth_key: Option<String>,
+    signed_request: Option<SignedRequest>,
+    required_scope: AdminScope,
+) -> Result<&'a AdminKey, HallError> {
+    let keys = config.admin_keys.as_ref()
+        .ok_or_else(|| HallError::Forbidden("The admin interface is currently disabled".to_string()))?;
+
+    let key = if let Some(signed_request) = signed_request {
+        verify_signed_request(keys, config, signed_request)?
+    } else if config.allow_bearer_auth {
+        auth_key
+            .and_then(|unchecked_key| keys.iter().find(|key| key.key == unchecked_key))
+            .ok_or_else(|| HallError::Forbidden("Invalid key".to_string()))?
+    } else {
+        return Err(HallError::Forbidden("Invalid key".to_string()));
+    };
+
+    if key.scope.allows(required_scope) {
+        Ok(key)
+    } else {
+        Err(HallError::Forbidden("This key is not permitted to perform this action".to_string()))
+    }
+}
+
+fn verify_signed_request<'a>(
+    keys: &'a [AdminKey],
+    config: &Config,
+    req: SignedRequest,
+) -> Result<&'a AdminKey, HallError> {
+    let key = keys.iter().find(|key| key.username == req.key_id)
+        .ok_or_else(|| HallError::Forbidden("Invalid key".to_string()))?;
+
+    let now = chrono::Utc::now().timestamp();
+    if (now - req.timestamp).abs() > config.hmac_time_window_secs {
+        return Err(HallError::Forbidden(
+            "Request timestamp is outside the allowed window".to_string()
+        ));
+    }
+
+    let body_hash = hex::encode(Sha256::digest(&req.body));
+    let canonical_request = format!("{}\n{}\n{}\n{}", req.method, req.path, req.timestamp, body_hash);
+
+    let mut mac = HmacSha256::new_varkey(key.hmac_secret.as_bytes())
+        .map_err(|_| HallError::Forbidden("Invalid key".to_string()))?;
+    mac.update(canonical_request.as_bytes());
+    let expected_signature = mac.finalize().into_bytes();
+
+    let provided_signature = hex::decode(&req.signature)
+        .map_err(|_| HallError::BadRequest("The signature is not valid hex".to_string()))?;
+
+    if expected_signature.as_slice().ct_eq(&provided_signature).unwrap_u8() == 1 {
+        Ok(key)
+    } else {
+        Err(HallError::Forbidden("Invalid signature".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, ServerConfig};
+
+    fn test_config(keys: Vec<AdminKey>) -> Config {
+        Config {
+            project_name: "Test Hall".to_string(),
+            logging_dir: "logs".to_string(),
+            logging_level: "info".to_string(),
+            server: ServerConfig { ip: "127.0.0.1".parse().unwrap(), port: 8080 },
+            admin_keys: Some(keys),
+            allow_bearer_auth: false,
+            hmac_time_window_secs: 300,
+            encryption_key: None,
+            id_salt: "test-salt".to_string(),
+        }
+    }
+
+    fn test_key(scope: AdminScope) -> AdminKey {
+        AdminKey {
+            username: "alice".to_string(),
+            key: "bearer-token".to_string(),
+            hmac_secret: "hmac-secret".to_string(),
+            signing_key: None,
+            scope,
+        }
+    }
+
+    fn sign(key: &str, method: &str, path: &str, timestamp: i64, body: &[u8]) -> String {
+        let body_hash = hex::encode(Sha256::digest(body));
+        let canonical_request = format!("{}\n{}\n{}\n{}", method, path, timestamp, body_hash);
+        let mut mac = HmacSha256::new_varkey(key.as_bytes()).unwrap();
+        mac.update(canonical_request.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn accepts_a_correctly_signed_request() {
+        let config = test_config(vec![test_key(AdminScope::ReadWrite)]);
+        let now = chrono::Utc::now().timestamp();
+        let signature = sign("hmac-secret", "GET", "/admin/list", now, b"");
+
+        let req = SignedRequest {
+            key_id: "alice".to_string(),
+            timestamp: now,
+            signature,
+            method: "GET".to_string(),
+            path: "/admin/list".to_string(),
+            body: Vec::new(),
+        };
+
+        let result = check_admin_permissions(&config, None, Some(req), AdminScope::ReadOnly);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn rejects_a_tampered_signature() {
+        let config = test_config(vec![test_key(AdminScope::ReadWrite)]);
+        let now = chrono::Utc::now().timestamp();
+        let signature = sign("hmac-secret", "GET", "/admin/list", now, b"");
+
+        let req = SignedRequest {
+            key_id: "alice".to_string(),
+            timestamp: now,
+            signature,
+            method: "GET".to_string(),
+            path: "/admin/other".to_string(),
+            body: Vec::new(),
+        };
+
+        let result = check_admin_permissions(&config, None, Some(req), AdminScope::ReadOnly);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_a_request_outside_the_replay_window() {
+        let config = test_config(vec![test_key(AdminScope::ReadWrite)]);
+        let stale_timestamp = chrono::Utc::now().timestamp() - 10_000;
+        let signature = sign("hmac-secret", "GET", "/admin/list", stale_timestamp, b"");
+
+        let req = SignedRequest {
+            key_id: "alice".to_string(),
+            timestamp: stale_timestamp,
+            signature,
+            method: "GET".to_string(),
+            path: "/admin/list".to_string(),
+            body: Vec::new(),
+        };
+
+        let result = check_admin_permissions(&config, None, Some(req), AdminScope::ReadOnly);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_a_read_only_key_for_a_read_write_action() {
+        let config = test_config(vec![test_key(AdminScope::ReadOnly)]);
+        let now = chrono::Utc::now().timestamp();
+        let signature = sign("hmac-secret", "POST", "/admin/add", now, b"body");
+
+        let req = SignedRequest {
+            key_id: "alice".to_string(),
+            timestamp: now,
+            signature,
+            method: "POST".to_string(),
+            path: "/admin/add".to_string(),
+            body: b"body".to_vec(),
+        };
+
+        let result = check_admin_permissions(&config, None, Some(req), AdminScope::ReadWrite);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use rand::RngCore;
+
+use crate::error::HallError;
+use crate::HallEntry;
+
+// Records written before encryption-at-rest existed are raw bincode with no
+// header at all, so a versioned record is prefixed with this magic tag before
+// its version byte. A legacy record's leading bytes are the little-endian
+// `id: u64` (the struct's first field), whose low four bytes would have to
+// equal this entire tag to be misread as versioned. Unlike a single sniffed
+// byte, which ids as small as 1 would hit, that needs an id of at least
+// 1,280,065,864.
+const MAGIC: [u8; 4] = *b"HALL";
+const VERSION_PLAINTEXT: u8 = 0x01;
+const VERSION_ENCRYPTED: u8 = 0x02;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts a serialized `HallEntry` for storage when `encryption_key` is
+/// configured (random IV prepended to the ciphertext, AES-256-GCM), or tags
+/// it as plaintext otherwise. Either way the result carries a version byte so
+/// `decrypt_record` can tell it apart from a pre-migration, unversioned record.
+pub fn encrypt_record(plaintext: &[u8], encryption_key: &Option<String>) -> Result<Vec<u8>, HallError> {
+    match encryption_key {
+        Some(key_hex) => {
+            let cipher = build_cipher(key_hex)?;
+
+            let mut nonce_bytes = [0u8; NONCE_LEN];
+            rand::thread_rng().fill_bytes(&mut nonce_bytes);
+            let nonce = Nonce::from_slice(&nonce_bytes);
+
+            let ciphertext = cipher.encrypt(nonce, plaintext)
+                .map_err(|_| HallError::BadRequest("Failed to encrypt record".to_string()))?;
+
+            let mut stored = Vec::with_capacity(HEADER_LEN + NONCE_LEN + ciphertext.len());
+            stored.extend_from_slice(&MAGIC);
+            stored.push(VERSION_ENCRYPTED);
+            stored.extend_from_slice(&nonce_bytes);
+            stored.extend_from_slice(&ciphertext);
+            Ok(stored)
+        }
+        None => {
+            let mut stored = Vec::with_capacity(HEADER_LEN + plaintext.len());
+            stored.extend_from_slice(&MAGIC);
+            stored.push(VERSION_PLAINTEXT);
+            stored.extend_from_slice(plaintext);
+            Ok(stored)
+        }
+    }
+}
+
+/// Reverses `encrypt_record`. Pre-migration records (written before this
+/// feature existed) have no header at all and are returned unchanged.
+pub fn decrypt_record(stored: &[u8], encryption_key: &Option<String>) -> Result<Vec<u8>, HallError> {
+    if stored.len() < HEADER_LEN || stored[..MAGIC.len()] != MAGIC[..] {
+        return Ok(stored.to_vec());
+    }
+
+    match stored[MAGIC.len()] {
+        VERSION_ENCRYPTED => {
+            let key_hex = encryption_key.as_ref().ok_or_else(|| HallError::BadRequest(
+                "This record is encrypted but no encryption_key is configured".to_string()
+            ))?;
+            let cipher = build_cipher(key_hex)?;
+
+            if stored.len() < HEADER_LEN + NONCE_LEN {
+                return Err(HallError::BadRequest("Stored record is truncated".to_string()));
+            }
+            let nonce = Nonce::from_slice(&stored[HEADER_LEN..HEADER_LEN + NONCE_LEN]);
+            let ciphertext = &stored[HEADER_LEN + NONCE_LEN..];
+
+            cipher.decrypt(nonce, ciphertext).map_err(|_| HallError::BadRequest(
+                "Failed to decrypt record, it may be corrupt or the encryption_key is wrong".to_string()
+            ))
+        }
+        VERSION_PLAINTEXT => Ok(stored[HEADER_LEN..].to_vec()),
+        _ => Err(HallError::BadRequest("Stored record has an unrecognized version tag".to_string()))
+    }
+}
+
+fn build_cipher(key_hex: &str) -> Result<Aes256Gcm, HallError> {
+    let key_bytes = hex::decode(key_hex)
+        .map_err(|_| HallError::BadRequest("The configured encryption_key is not valid hex".to_string()))?;
+    if key_bytes.len() != 32 {
+        return Err(HallError::BadRequest("encryption_key must decode to exactly 32 bytes".to_string()));
+    }
+
+    Ok(Aes256Gcm::new(Key::from_slice(&key_bytes)))
+}
+
+/// Signs `entry` in place with the ed25519 secret key (hex-encoded) configured
+/// for the admin key that created or updated it, storing the detached
+/// signature and the matching public key alongside the record. The signature
+/// covers every field of the entry except the signature/public-key pair
+/// themselves.
+pub fn sign_entry(entry: &mut HallEntry, secret_key_hex: &str) -> Result<(), HallError> {
+    let secret_bytes = hex::decode(secret_key_hex)
+        .map_err(|_| HallError::BadRequest("The configured signing key is not valid hex".to_string()))?;
+    let secret_key = SecretKey::from_bytes(&secret_bytes)
+        .map_err(|_| HallError::BadRequest("The configured signing key is malformed".to_string()))?;
+    let public_key = PublicKey::from(&secret_key);
+    let keypair = Keypair { secret: secret_key, public: public_key };
+
+    entry.signer_public_key = None;
+    entry.signature = None;
+    let canonical_bytes = bincode::serialize(entry)?;
+
+    let signature = keypair.sign(&canonical_bytes);
+    entry.signer_public_key = Some(hex::encode(public_key.as_bytes()));
+    entry.signature = Some(hex::encode(signature.to_bytes().to_vec()));
+
+    Ok(())
+}
+
+/// Re-serializes `entry` with its signature fields cleared and checks that
+/// the stored signature still matches. Used by `list_records` to flag any
+/// record whose stored bytes no longer match what was originally signed.
+pub fn verify_entry(entry: &HallEntry) -> bool {
+    let public_key_hex = match &entry.signer_public_key {
+        Some(value) => value,
+        None => return false
+    };
+    let signature_hex = match &entry.signature {
+        Some(value) => value,
+        None => return false
+    };
+
+    let public_key_bytes = match hex::decode(public_key_hex) {
+        Ok(bytes) => bytes,
+        Err(_) => return false
+    };
+    let signature_bytes = match hex::decode(signature_hex) {
+        Ok(bytes) => bytes,
+        Err(_) => return false
+    };
+
+    let public_key = match PublicKey::from_bytes(&public_key_bytes) {
+        Ok(key) => key,
+        Err(_) => return false
+    };
+    let signature = match Signature::from_bytes(&signature_bytes) {
+        Ok(sig) => sig,
+        Err(_) => return false
+    };
+
+    let mut unsigned_entry = entry.clone();
+    unsigned_entry.signer_public_key = None;
+    unsigned_entry.signature = None;
+
+    let canonical_bytes = match bincode::serialize(&unsigned_entry) {
+        Ok(bytes) => bytes,
+        Err(_) => return false
+    };
+
+    public_key.verify(&canonical_bytes, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_entry(id: u64) -> HallEntry {
+        HallEntry {
+            id,
+            anchor_key: None,
+            reference_id: 42,
+            affected_service: "gearbot-api".to_string(),
+            date: chrono::NaiveDate::from_ymd(2026, 1, 1),
+            summary: "a summary".to_string(),
+            reporter: "someone".to_string(),
+            reporter_handle: None,
+            signer_public_key: None,
+            signature: None,
+            verified: false,
+        }
+    }
+
+    #[test]
+    fn encrypt_then_decrypt_round_trips() {
+        let plaintext = b"hello world".to_vec();
+        let key_hex = hex::encode([0x11u8; 32]);
+
+        let stored = encrypt_record(&plaintext, &Some(key_hex.clone())).unwrap();
+        let decrypted = decrypt_record(&stored, &Some(key_hex)).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn plaintext_tagged_records_round_trip_with_no_key_configured() {
+        let plaintext = b"hello world".to_vec();
+
+        let stored = encrypt_record(&plaintext, &None).unwrap();
+        let decrypted = decrypt_record(&stored, &None).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn legacy_unversioned_records_round_trip_unchanged_even_with_a_colliding_id() {
+        // Regression test: a pre-migration record is raw bincode with no
+        // header, and its leading bytes are the little-endian `id: u64`. An
+        // id whose low byte equals a version tag must not be misread as a
+        // versioned record; only the full 4-byte magic tag marks one.
+        let entry = test_entry(u64::from(VERSION_ENCRYPTED));
+        let legacy_bytes = bincode::serialize(&entry).unwrap();
+
+        let decrypted = decrypt_record(&legacy_bytes, &None).unwrap();
+
+        assert_eq!(decrypted, legacy_bytes);
+    }
+
+    #[test]
+    fn sign_then_verify_succeeds() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[0x42u8; 32]).unwrap();
+        let secret_hex = hex::encode(secret.as_bytes());
+
+        let mut entry = test_entry(1);
+        sign_entry(&mut entry, &secret_hex).unwrap();
+
+        assert!(verify_entry(&entry));
+    }
+
+    #[test]
+    fn tampering_after_signing_fails_verification() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[0x42u8; 32]).unwrap();
+        let secret_hex = hex::encode(secret.as_bytes());
+
+        let mut entry = test_entry(1);
+        sign_entry(&mut entry, &secret_hex).unwrap();
+        entry.summary = "a different summary".to_string();
+
+        assert!(!verify_entry(&entry));
+    }
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::fmt;
+
+use warp::{http::StatusCode, reject::Reject};
+
+/// The single error type for every fallible operation in the hall: database
+/// I/O, (de)serialization, template rendering, and request-level failures
+/// that should be reported back to the caller as a specific status code.
+#[derive(Debug)]
+pub enum HallError {
+    Sled(sled::Error),
+    Codec(bincode::Error),
+    Json(serde_json::Error),
+    Template(askama::Error),
+    BadRequest(String),
+    NotFound(String),
+    Forbidden(String),
+}
+
+impl HallError {
+    pub fn as_code(&self) -> StatusCode {
+        match self {
+            HallError::Sled(_) | HallError::Codec(_) | HallError::Json(_) | HallError::Template(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            HallError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            HallError::NotFound(_) => StatusCode::NOT_FOUND,
+            HallError::Forbidden(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.as_code().as_u16()
+    }
+}
+
+impl fmt::Display for HallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HallError::Sled(e) => write!(f, "A database error occurred: {}", e),
+            HallError::Codec(e) => write!(f, "Failed to read or write a record: {}", e),
+            HallError::Json(e) => write!(f, "Failed to read or write a JSON document: {}", e),
+            HallError::Template(e) => write!(f, "Failed to render a template: {}", e),
+            HallError::BadRequest(msg) => f.write_str(msg),
+            HallError::NotFound(msg) => f.write_str(msg),
+            HallError::Forbidden(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for HallError {}
+impl Reject for HallError {}
+
+impl From<sled::Error> for HallError {
+    fn from(e: sled::Error) -> Self {
+        HallError::Sled(e)
+    }
+}
+
+impl From<bincode::Error> for HallError {
+    fn from(e: bincode::Error) -> Self {
+        HallError::Codec(e)
+    }
+}
+
+impl From<serde_json::Error> for HallError {
+    fn from(e: serde_json::Error) -> Self {
+        HallError::Json(e)
+    }
+}
+
+impl From<askama::Error> for HallError {
+    fn from(e: askama::Error) -> Self {
+        HallError::Template(e)
+    }
+}
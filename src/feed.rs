@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sled::Db;
+
+use crate::error::HallError;
+use crate::{list_records, Config, HallEntry, PublicRecord};
+
+#[derive(Debug, Serialize)]
+pub struct JsonFeed<'a> {
+    project_name: &'a str,
+    entries: Vec<PublicRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Acknowledgment {
+    reporter: String,
+    reporter_handle: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SecurityAcknowledgments<'a> {
+    project_name: &'a str,
+    acknowledgments: Vec<Acknowledgment>,
+}
+
+// Shared by every feed format: most-recent-first, then the optional
+// `?since=`/`?limit=` query filters so integrators can poll for new entries
+// instead of scraping HTML.
+fn filtered_entries(
+    record_db: &Db,
+    config: &Config,
+    since: Option<NaiveDate>,
+    limit: Option<usize>,
+) -> Result<Vec<HallEntry>, HallError> {
+    let mut entries = list_records(record_db, config)?;
+    entries.sort_by(|a, b| b.date.cmp(&a.date).then(b.id.cmp(&a.id)));
+
+    if let Some(since) = since {
+        entries.retain(|entry| entry.date >= since);
+    }
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+
+    Ok(entries)
+}
+
+pub fn build_json_feed<'a>(
+    record_db: &Db,
+    config: &'a Config,
+    since: Option<NaiveDate>,
+    limit: Option<usize>,
+) -> Result<JsonFeed<'a>, HallError> {
+    let entries = filtered_entries(record_db, config, since, limit)?
+        .iter()
+        .map(|entry| entry.to_public(config))
+        .collect();
+
+    Ok(JsonFeed { project_name: &config.project_name, entries })
+}
+
+pub fn build_rss_feed(
+    record_db: &Db,
+    config: &Config,
+    since: Option<NaiveDate>,
+    limit: Option<usize>,
+) -> Result<String, HallError> {
+    let entries = filtered_entries(record_db, config, since, limit)?;
+
+    let items: String = entries.iter().map(|entry| format!(
+        "    <item>\n      <title>{title}</title>\n      <pubDate>{date}</pubDate>\n      <guid isPermaLink=\"false\">{guid}</guid>\n      <description>{description}</description>\n    </item>\n",
+        title = xml_escape(&entry.affected_service),
+        date = entry.date.and_hms(0, 0, 0).format("%a, %d %b %Y %H:%M:%S +0000"),
+        guid = entry.public_id(config),
+        description = xml_escape(&entry.summary)
+    )).collect();
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n  <channel>\n    <title>{title}</title>\n    <description>Security acknowledgments for {title}</description>\n{items}  </channel>\n</rss>\n",
+        title = xml_escape(&config.project_name),
+        items = items
+    ))
+}
+
+pub fn build_security_acknowledgments(record_db: &Db, config: &Config) -> Result<SecurityAcknowledgments, HallError> {
+    let entries = list_records(record_db, config)?;
+
+    let mut seen_reporters = HashSet::new();
+    let mut acknowledgments = Vec::new();
+    for entry in entries {
+        if seen_reporters.insert(entry.reporter.clone()) {
+            acknowledgments.push(Acknowledgment {
+                reporter: entry.reporter,
+                reporter_handle: entry.reporter_handle,
+            });
+        }
+    }
+
+    Ok(SecurityAcknowledgments { project_name: &config.project_name, acknowledgments })
+}
+
+fn xml_escape(input: &str) -> String {
+    input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
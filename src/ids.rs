@@ -0,0 +1,110 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sqids::Sqids;
+
+use crate::error::HallError;
+use crate::Config;
+
+const DEFAULT_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+// FNV-1a, 64-bit variant. Used instead of `std::collections::hash_map::
+// DefaultHasher` because the alphabet shuffle it seeds must stay identical
+// forever (every previously issued slug still has to decode), and the
+// standard library explicitly does not guarantee `DefaultHasher`'s algorithm
+// is stable across Rust versions.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a_hash(data: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// Deterministically shuffles the default sqids alphabet using `id_salt` as a
+// seed, so every deployment gets its own mapping between internal ids and
+// public slugs without needing to ship a custom alphabet in config.
+fn shuffled_alphabet(salt: &str) -> String {
+    let mut rng = StdRng::seed_from_u64(fnv1a_hash(salt.as_bytes()));
+
+    let mut chars: Vec<char> = DEFAULT_ALPHABET.chars().collect();
+    for i in (1..chars.len()).rev() {
+        let j = rng.gen_range(0..=i);
+        chars.swap(i, j);
+    }
+
+    chars.into_iter().collect()
+}
+
+fn build_sqids(config: &Config) -> Sqids {
+    Sqids::builder()
+        .alphabet(shuffled_alphabet(&config.id_salt).chars().collect())
+        .build()
+        .expect("a shuffled copy of the default alphabet is always valid")
+}
+
+/// Encodes an internal, monotonic record id into a short, non-sequential
+/// public slug. The `SI-{n}` sled storage key keeps using the raw id; only
+/// what's shown to and accepted from clients is obfuscated.
+pub fn encode_id(config: &Config, id: u64) -> String {
+    build_sqids(config).encode(&[id]).expect("encoding a single id never fails")
+}
+
+/// Decodes a public slug back into the internal record id, failing closed
+/// (as a `BadRequest`) on anything that doesn't round-trip.
+pub fn decode_id(config: &Config, slug: &str) -> Result<u64, HallError> {
+    let sqids = build_sqids(config);
+
+    match sqids.decode(slug).as_slice() {
+        [id] if sqids.encode(&[*id]).as_deref() == Ok(slug) => Ok(*id),
+        _ => Err(HallError::BadRequest("The provided ID could not be decoded".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServerConfig;
+
+    fn test_config(id_salt: &str) -> Config {
+        Config {
+            project_name: "Test Hall".to_string(),
+            logging_dir: "logs".to_string(),
+            logging_level: "info".to_string(),
+            server: ServerConfig { ip: "127.0.0.1".parse().unwrap(), port: 8080 },
+            admin_keys: None,
+            allow_bearer_auth: false,
+            hmac_time_window_secs: 300,
+            encryption_key: None,
+            id_salt: id_salt.to_string(),
+        }
+    }
+
+    #[test]
+    fn encode_then_decode_round_trips() {
+        let config = test_config("test-salt");
+
+        let slug = encode_id(&config, 12345);
+        let decoded = decode_id(&config, &slug).unwrap();
+
+        assert_eq!(decoded, 12345);
+    }
+
+    #[test]
+    fn different_salts_produce_different_slugs() {
+        let a = test_config("salt-a");
+        let b = test_config("salt-b");
+
+        assert_ne!(encode_id(&a, 1), encode_id(&b, 1));
+    }
+
+    #[test]
+    fn decode_fails_closed_on_garbage_input() {
+        let config = test_config("test-salt");
+
+        assert!(decode_id(&config, "not-a-real-slug").is_err());
+    }
+}
@@ -3,17 +3,19 @@
 
 use askama::Template;
 use bincode;
+use bytes::Bytes;
 use chrono::{Datelike, NaiveDate};
 use lazy_static::lazy_static;
 use serde::{Serialize, Deserialize};
-use sled::Db;
+use serde_json;
+use sled::{Db, Tree};
 use warp::{path, Filter, http::StatusCode};
 use warp::{Rejection, reply::Response, Reply};
 
-use log::info;
+use log::{error, info};
 use flexi_logger::{Duplicate, Logger};
 
-use std::{fmt, fs, error::Error};
+use std::fs;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::net::IpAddr;
@@ -24,10 +26,42 @@ struct ServerConfig {
     port: u16,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminScope {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl AdminScope {
+    // Whether a key with this scope may perform an action that requires `required`.
+    pub fn allows(self, required: AdminScope) -> bool {
+        self == AdminScope::ReadWrite || required == AdminScope::ReadOnly
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AdminKey {
     username: String,
+    // The plaintext bearer token, only ever checked when `allow_bearer_auth`
+    // is set. Kept separate from `hmac_secret` so observing one (e.g. in
+    // logs, or over a non-TLS hop) never hands out the other.
     key: String,
+    // The HMAC-SHA256 secret used to verify signed requests. Never
+    // transmitted by clients, unlike `key`.
+    hmac_secret: String,
+    // Hex-encoded ed25519 secret key used to sign the entries this key creates
+    // or updates. Entries are left unsigned when this is not configured.
+    #[serde(default)]
+    signing_key: Option<String>,
+    // Least-privilege by default: a key must opt in to read_write to do
+    // anything beyond listing records and reading the audit log.
+    #[serde(default = "default_admin_scope")]
+    scope: AdminScope,
+}
+
+fn default_admin_scope() -> AdminScope {
+    AdminScope::ReadOnly
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,14 +71,37 @@ struct Config {
     logging_level: String,
     server: ServerConfig,
     admin_keys: Option<Vec<AdminKey>>,
+    // The plaintext bearer-token path leaks the key on non-TLS deployments, so it
+    // stays opt-in; HMAC-signed requests (see `auth.rs`) are always accepted.
+    #[serde(default)]
+    allow_bearer_auth: bool,
+    #[serde(default = "default_hmac_time_window_secs")]
+    hmac_time_window_secs: i64,
+    // Hex-encoded AES-256 key (32 bytes). When set, record values are
+    // encrypted at rest; existing unencrypted records keep working and are
+    // migrated to the encrypted format the next time they're written.
+    #[serde(default)]
+    encryption_key: Option<String>,
+    // Seeds the per-deployment sqid alphabet used to obfuscate public record
+    // ids; change this to invalidate every previously issued slug.
+    #[serde(default = "default_id_salt")]
+    id_salt: String,
+}
+
+fn default_id_salt() -> String {
+    "change-me".to_string()
 }
 
-#[derive(Debug, Deserialize, Serialize, Hash)]
+fn default_hmac_time_window_secs() -> i64 {
+    300
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Hash)]
 pub struct HallEntry {
     // This ID is randomly assigned and used for updates/deletions
     id: u64,
     anchor_key: Option<String>,
-    // This ID is submitted by the user for linking to reports, incidents, etc. 
+    // This ID is submitted by the user for linking to reports, incidents, etc.
     reference_id: u64,
     affected_service: String,
     date: NaiveDate,
@@ -52,6 +109,15 @@ pub struct HallEntry {
     reporter: String,
     // This allows for a user to specify a handle, Twitter profile, etc to be displayed by their name.
     reporter_handle: Option<String>,
+    // Hex-encoded ed25519 public key and detached signature over this entry
+    // (with both of these fields cleared), set by `crypto::sign_entry`.
+    #[serde(default)]
+    signer_public_key: Option<String>,
+    #[serde(default)]
+    signature: Option<String>,
+    // Recomputed on every `list_records` call, never persisted.
+    #[serde(skip, default)]
+    verified: bool,
 }
 
 impl HallEntry {
@@ -63,13 +129,56 @@ impl HallEntry {
         // The anchors will end up similar to #2019-5B2CBFE78ED4BD69
         self.anchor_key = Some(format!("{}-{:X}", self.date.year(), hash))
     }
+
+    // The short, non-sequential slug shown to and accepted from clients in
+    // place of the raw, enumerable `id`.
+    pub fn public_id(&self, config: &Config) -> String {
+        ids::encode_id(config, self.id)
+    }
+
+    // The view of this entry exposed to API clients: the raw, enumerable
+    // `id` never leaves the server, only its obfuscated slug.
+    pub fn to_public(&self, config: &Config) -> PublicRecord {
+        PublicRecord {
+            id: self.public_id(config),
+            anchor_key: self.anchor_key.clone(),
+            reference_id: self.reference_id,
+            affected_service: self.affected_service.clone(),
+            date: self.date,
+            summary: self.summary.clone(),
+            reporter: self.reporter.clone(),
+            reporter_handle: self.reporter_handle.clone(),
+            signer_public_key: self.signer_public_key.clone(),
+            signature: self.signature.clone(),
+            verified: self.verified,
+        }
+    }
+}
+
+// The public, client-facing view of a `HallEntry`: identical except that the
+// internal, sequential `id` is replaced by its sqid slug, so the ids in
+// `/admin/list` and `/feed.json` can't be used to guess other records' ids
+// or how many records have ever been created.
+#[derive(Debug, Serialize)]
+pub struct PublicRecord {
+    id: String,
+    anchor_key: Option<String>,
+    reference_id: u64,
+    affected_service: String,
+    date: NaiveDate,
+    summary: String,
+    reporter: String,
+    reporter_handle: Option<String>,
+    signer_public_key: Option<String>,
+    signature: Option<String>,
+    verified: bool,
 }
 
 // This is the data that is needed in a POST to create a new record
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RecordSubmission {
-    // This ID is used for updating posts only. It is ignored elsewhere.
-    id: Option<u64>,
+    // The public sqid slug, used for updating posts only. It is ignored elsewhere.
+    id: Option<String>,
     reference_id: u64,
     affected_service: String,
     // This is submitted in the form of Y-M-D
@@ -79,41 +188,35 @@ pub struct RecordSubmission {
     reporter_handle: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-struct OperationResponse {
-    code: u16,
-    message: String
-}
-
-#[derive(Copy, Clone, Debug)]
-enum HallError {
-    Failed,
-    BadRequest,
+// The data sent to PATCH an existing record. Unlike `RecordSubmission`, every
+// mutable field is optional; only `Some(..)` values overwrite the loaded
+// `HallEntry`, the rest are left as `..old_record`. `reporter_handle` is
+// wrapped twice since `Some(None)` means "clear it" and `None` means "leave it".
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordPatch {
+    id: String,
+    reference_id: Option<u64>,
+    affected_service: Option<String>,
+    date: Option<NaiveDate>,
+    summary: Option<String>,
+    reporter: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    reporter_handle: Option<Option<String>>,
 }
 
-// This exists only to handle unexpected errors due to bad user input
-impl HallError {
-    fn as_code(self) -> StatusCode {
-        match self {
-            HallError::Failed => StatusCode::INTERNAL_SERVER_ERROR,
-            HallError::BadRequest => StatusCode::BAD_REQUEST
-        }
-    }
-
-    fn as_u16(self) -> u16 {
-        self.as_code().as_u16()
-    }
+// Maps a field that is present (even as `null`) to `Some(..)`, so a JSON
+// `null` arrives as `Some(None)`; an absent field stays `None` via `default`.
+fn deserialize_present<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer).map(Some)
 }
 
-impl Error for HallError {}
-
-impl fmt::Display for HallError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(match self {
-            HallError::Failed => "The requested operation failed, please try again.",
-            HallError::BadRequest => "Your request was malformed, please modify it and try again."
-        })
-    }
+#[derive(Debug, Serialize)]
+struct OperationResponse {
+    code: u16,
+    message: String
 }
 
 #[derive(Debug, Template)]
@@ -127,9 +230,30 @@ mod admin;
 use admin::{
     add_record,
     remove_record,
-    update_record
+    update_record,
+    patch_record
 };
 
+mod error;
+use error::HallError;
+
+mod auth;
+use auth::{check_admin_permissions, SignedRequest};
+
+mod crypto;
+
+mod ids;
+
+mod feed;
+
+mod audit;
+
+#[derive(Debug, Deserialize)]
+struct FeedQuery {
+    since: Option<NaiveDate>,
+    limit: Option<usize>,
+}
+
 
 lazy_static! {
     static ref CONFIG: Config = {
@@ -145,6 +269,12 @@ lazy_static! {
     };
 }
 
+lazy_static! {
+    static ref AUDIT_LOG: Tree = {
+        RECORD_DB.open_tree("audit_log").unwrap()
+    };
+}
+
 fn main() {
     Logger::with_str(&CONFIG.logging_level)
         .log_to_file()
@@ -159,95 +289,180 @@ fn main() {
     // Pre-initalize the database for about a ~500ms faster first load
     RECORD_DB.get("_").unwrap();
 
-    let main_page = warp::path::end().map(||
-        warp::reply::html(generate_record_page(&RECORD_DB, &CONFIG))
+    let main_page = warp::path::end().and_then(||
+        generate_record_page(&RECORD_DB, &CONFIG)
+            .map(|html| warp::reply::html(html).into_response())
+            .map_err(warp::reject::custom)
     );
 
     let static_content = path!("static").and(warp::fs::dir("static"));
 
+    let feed_json = path!("feed.json")
+        .and(warp::query::<FeedQuery>())
+        .and_then(|query: FeedQuery|
+            feed::build_json_feed(&RECORD_DB, &CONFIG, query.since, query.limit)
+                .map(|feed| warp::reply::json(&feed).into_response())
+                .map_err(warp::reject::custom)
+        );
+
+    let feed_rss = path!("feed.rss")
+        .and(warp::query::<FeedQuery>())
+        .and_then(|query: FeedQuery|
+            feed::build_rss_feed(&RECORD_DB, &CONFIG, query.since, query.limit)
+                .map(|xml| warp::reply::with_header(xml, "Content-Type", "application/rss+xml").into_response())
+                .map_err(warp::reject::custom)
+        );
+
+    let security_acknowledgments = path!(".well-known" / "security-acknowledgments.json")
+        .and_then(||
+            feed::build_security_acknowledgments(&RECORD_DB, &CONFIG)
+                .map(|acks| warp::reply::json(&acks).into_response())
+                .map_err(warp::reject::custom)
+        );
+
 
     let get_key = warp::header::optional::<String>("Authorization");
+    let signing_headers = warp::header::optional::<String>("X-Hall-Key-Id")
+        .and(warp::header::optional::<i64>("X-Hall-Timestamp"))
+        .and(warp::header::optional::<String>("X-Hall-Signature"));
+    let request_identity = warp::method().and(warp::path::full()).and(signing_headers).and(get_key);
 
     let record_listings = path!("list")
-        .and(get_key)
-        .map(|auth_key: Option<String>|
-            match check_admin_permissions(&CONFIG, auth_key) {
-                Ok(_) => warp::reply::json(&list_records(&RECORD_DB)).into_response(),
-                Err(e) => e
-            }
-        );
+        .and(request_identity.clone())
+        .and_then(|method, path, key_id, timestamp, signature, auth_key: Option<String>| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &[]);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadOnly)
+                .and_then(|_| list_records(&RECORD_DB, &CONFIG))
+                .map(|records| {
+                    let public: Vec<PublicRecord> = records.iter().map(|r| r.to_public(&CONFIG)).collect();
+                    warp::reply::json(&public).into_response()
+                })
+                .map_err(warp::reject::custom)
+        });
+
+    let audit_listings = path!("audit")
+        .and(request_identity.clone())
+        .and_then(|method, path, key_id, timestamp, signature, auth_key: Option<String>| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &[]);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadOnly)
+                .and_then(|_| audit::list_actions(&AUDIT_LOG))
+                .map(|entries| warp::reply::json(&entries).into_response())
+                .map_err(warp::reject::custom)
+        });
 
     let record_add = path!("add")
-        .and(warp::body::json().and(get_key))
-        .map(|new_record: RecordSubmission, auth_key: Option<String>|
-            match check_admin_permissions(&CONFIG, auth_key) {
-                Ok(user) => add_record(new_record, user, &RECORD_DB),
-                Err(e) => e
-            }
-        );
+        .and(request_identity.clone())
+        .and(warp::body::bytes())
+        .and_then(|method, path, key_id, timestamp, signature, auth_key: Option<String>, body: Bytes| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &body);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadWrite)
+                .and_then(|user| {
+                    let new_record: RecordSubmission = serde_json::from_slice(&body)
+                        .map_err(|_| HallError::BadRequest("The request body is not a valid record".to_string()))?;
+                    add_record(new_record, user, &RECORD_DB, &CONFIG, &AUDIT_LOG)
+                })
+                .map_err(warp::reject::custom)
+        });
+
 
-    
     let record_remove = path!("remove")
-        .and(warp::path::param().and(get_key))
-        .map(|id: u64, auth_key: Option<String>|
-            match check_admin_permissions(&CONFIG, auth_key) {
-                Ok(user) => remove_record(id, user, &RECORD_DB),
-                Err(e) => e
-            }
-        );
+        .and(warp::path::param())
+        .and(request_identity.clone())
+        .and_then(|slug: String, method, path, key_id, timestamp, signature, auth_key: Option<String>| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &[]);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadWrite)
+                .and_then(|user| remove_record(&slug, user, &RECORD_DB, &CONFIG, &AUDIT_LOG))
+                .map_err(warp::reject::custom)
+        });
 
     let record_update = path!("update")
-        .and(warp::body::json().and(get_key))
-        .map(|updated_record: RecordSubmission, auth_key: Option<String>|
-            match check_admin_permissions(&CONFIG, auth_key) {
-                Ok(user) => update_record(updated_record, user, &RECORD_DB),
-                Err(e) => e
-            }
-        );
-
-
-    let admin_get_interface = path!("admin").and(record_listings);
-    let admin_post_interface = path!("admin").and(record_add.or(record_update).or(record_remove))
+        .and(request_identity.clone())
+        .and(warp::body::bytes())
+        .and_then(|method, path, key_id, timestamp, signature, auth_key: Option<String>, body: Bytes| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &body);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadWrite)
+                .and_then(|user| {
+                    let updated_record: RecordSubmission = serde_json::from_slice(&body)
+                        .map_err(|_| HallError::BadRequest("The request body is not a valid record".to_string()))?;
+                    update_record(updated_record, user, &RECORD_DB, &CONFIG, &AUDIT_LOG)
+                })
+                .map_err(warp::reject::custom)
+        });
+
+
+    let record_patch = path!("patch")
+        .and(request_identity.clone())
+        .and(warp::body::bytes())
+        .and_then(|method, path, key_id, timestamp, signature, auth_key: Option<String>, body: Bytes| {
+            let signed_request = build_signed_request(method, path, key_id, timestamp, signature, &body);
+            check_admin_permissions(&CONFIG, auth_key, signed_request, AdminScope::ReadWrite)
+                .and_then(|user| {
+                    let patch: RecordPatch = serde_json::from_slice(&body)
+                        .map_err(|_| HallError::BadRequest("The request body is not a valid patch".to_string()))?;
+                    patch_record(patch, user, &RECORD_DB, &CONFIG, &AUDIT_LOG)
+                })
+                .map_err(warp::reject::custom)
+        });
+
+    let admin_get_interface = path!("admin").and(record_listings.or(audit_listings));
+    let admin_post_interface = path!("admin").and(record_add.or(record_update).or(record_remove).or(record_patch))
         .recover(handle_errors);
 
-    let get_routes = warp::get2().and(main_page.or(admin_get_interface))
+    let get_routes = warp::get2()
+        .and(main_page.or(admin_get_interface).or(feed_json).or(feed_rss).or(security_acknowledgments))
         .recover(handle_errors);
-    
+
     warp::serve(get_routes.or(static_content).or(admin_post_interface)).run((CONFIG.server.ip, CONFIG.server.port))
 }
 
-fn check_admin_permissions(config: &Config, auth_key: Option<String>) -> Result<&AdminKey, Response>  {
-    if let Some(keys) = &config.admin_keys {
-        let bad_key_resp = generate_response("Invalid key", StatusCode::FORBIDDEN);
-        match auth_key {
-            Some(unchecked_key) => {
-                match keys.iter().find(|key| key.key == unchecked_key) {
-                    Some(valid_key) => Ok(valid_key),
-                    None => Err(bad_key_resp)
-                }
-            }
-            None => Err(bad_key_resp)
-        }
-    } else {
-        let err_msg = "The admin interface is currently disabled";
-        Err(generate_response(err_msg, StatusCode::FORBIDDEN))
+// Builds the optional HMAC `SignedRequest` from the per-request signing
+// headers. All three headers must be present for a request to be treated
+// as signed; otherwise it falls back to the bearer-token path.
+fn build_signed_request(
+    method: warp::http::Method,
+    path: warp::path::FullPath,
+    key_id: Option<String>,
+    timestamp: Option<i64>,
+    signature: Option<String>,
+    body: &[u8],
+) -> Option<SignedRequest> {
+    match (key_id, timestamp, signature) {
+        (Some(key_id), Some(timestamp), Some(signature)) => Some(SignedRequest {
+            key_id,
+            timestamp,
+            signature,
+            method: method.as_str().to_string(),
+            path: path.as_str().to_string(),
+            body: body.to_vec(),
+        }),
+        _ => None,
     }
 }
 
-fn generate_record_page(db: &Db, config: &Config) -> String {
-    let record_list = ReportList { project_name: &config.project_name, reports: list_records(db) };
-    record_list.render().unwrap()
+fn generate_record_page(db: &Db, config: &Config) -> Result<String, HallError> {
+    let record_list = ReportList { project_name: &config.project_name, reports: list_records(db, config)? };
+    Ok(record_list.render()?)
 }
 
-pub fn list_records(record_db: &Db) -> Vec<HallEntry> {
+pub fn list_records(record_db: &Db, config: &Config) -> Result<Vec<HallEntry>, HallError> {
     let mut decoded_records: Vec<HallEntry> = Vec::with_capacity(10);
 
     let all_records = record_db.scan_prefix("SI-");
     for report in all_records.values() {
-        decoded_records.push(bincode::deserialize(&report.unwrap()).unwrap())
+        let report = report?;
+        let decoded = crypto::decrypt_record(&report, &config.encryption_key)
+            .and_then(|decrypted| Ok(bincode::deserialize::<HallEntry>(&decrypted)?));
+
+        match decoded {
+            Ok(mut entry) => {
+                entry.verified = crypto::verify_entry(&entry);
+                decoded_records.push(entry);
+            }
+            Err(e) => error!("Skipping unreadable record while listing: {}", e)
+        }
     }
 
-    decoded_records
+    Ok(decoded_records)
 }
 
 fn generate_response(resp_message: &str, status_code: StatusCode) -> Response {
@@ -259,27 +474,42 @@ fn generate_response(resp_message: &str, status_code: StatusCode) -> Response {
     warp::reply::with_status(response, status_code).into_response()
 }
 
-// Any errors that are not user generated should become just a generic error
+fn format_error(status: StatusCode, message: String) -> impl Reply {
+    let resp_json = warp::reply::json(&OperationResponse {
+        code: status.as_u16(),
+        message
+    });
+
+    warp::reply::with_status(resp_json, status)
+}
+
+// Every `HallError` raised by our own handlers, and warp's own 400 and 404
+// rejections (e.g. a malformed query or header), are turned into the same
+// `OperationResponse` shape here. Any other rejection is passed on unchanged.
 fn handle_errors(err: warp::Rejection) -> Result<impl Reply, Rejection> {
+    if let Some(hall_error) = err.find_cause::<HallError>() {
+        let (code, message) = match hall_error {
+            // Internal failures (DB I/O, (de)serialization, templating) are logged
+            // with full detail but never leak their cause to the client.
+            HallError::Sled(_) | HallError::Codec(_) | HallError::Json(_) | HallError::Template(_) => {
+                error!("Internal error handling request: {}", hall_error);
+                (hall_error.as_code(), "The requested operation failed, please try again.".to_string())
+            }
+            _ => (hall_error.as_code(), hall_error.to_string())
+        };
+
+        return Ok(format_error(code, message));
+    }
+
     match err.status() {
-        StatusCode::INTERNAL_SERVER_ERROR => {
-            let error = HallError::Failed;
-            let resp_json = warp::reply::json(&OperationResponse {
-                code: error.as_u16(),
-                message: error.to_string()
-            });
-
-            Ok(warp::reply::with_status(resp_json, error.as_code()))
-        }
-        StatusCode::BAD_REQUEST => {
-            let error = HallError::BadRequest;
-            let resp_json = warp::reply::json(&OperationResponse {
-                code: error.as_u16(),
-                message: error.to_string()
-            });
-
-           Ok(warp::reply::with_status(resp_json, error.as_code()))
-        }
+        StatusCode::BAD_REQUEST => Ok(format_error(
+            StatusCode::BAD_REQUEST,
+            "Your request was malformed, please modify it and try again.".to_string()
+        )),
+        StatusCode::NOT_FOUND => Ok(format_error(
+            StatusCode::NOT_FOUND,
+            "The requested resource could not be found.".to_string()
+        )),
         _ => Err(err)
     }
 }